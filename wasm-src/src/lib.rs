@@ -232,7 +232,20 @@ impl Engine {
         Ok(CompiledPolicy::new(compiled_policy))
     }
 
-
+    /// Lint the loaded policies and return a JSON array of diagnostics:
+    /// `{ rule_id, severity, message, file, span: {start, end}, suggested_edits: [{span, replacement}] }`.
+    ///
+    /// Runs the rules from [`lint::default_rules`] over [`Engine::get_ast_as_json`]'s
+    /// output. This crate only ever sees the AST as that JSON (there's no typed AST
+    /// exposed alongside `Engine`), so every rule below walks the generic
+    /// `serde_json::Value` tree by field name rather than matching on AST node enums.
+    #[cfg(feature = "ast")]
+    pub fn lintPolicies(&self) -> Result<String, JsValue> {
+        let ast_json = self.engine.get_ast_as_json().map_err(error_to_jsvalue)?;
+        let ast: serde_json::Value = serde_json::from_str(&ast_json).map_err(error_to_jsvalue)?;
+        let diagnostics = lint::run(&ast);
+        serde_json::to_string(&diagnostics).map_err(error_to_jsvalue)
+    }
 }
 
 
@@ -261,6 +274,44 @@ impl CompiledPolicy {
         result.to_json_str().map_err(error_to_jsvalue)
     }
 
+    /// Evaluate a fixed batch of test cases against this compiled policy and report
+    /// pass/fail per case, for conformance/regression testing.
+    ///
+    /// * `vectors_json`: JSON array of `{ name, input, data?, expected }`. A case's
+    ///   `data` field is rejected with an error rather than silently ignored -
+    ///   `eval_with_input` has no per-call data override in this crate's surface, so
+    ///   there's no way to honor it here; every case instead runs against the data
+    ///   baked in when the policy was compiled. Use [`RegoVM::runTestVectors`] for
+    ///   cases that need per-case data.
+    ///
+    /// Returns `{ total, passed, failed, cases: [{ name, status, actual, expected, diff? }] }`,
+    /// where `diff` is the JSON-pointer-style path of the first mismatch for a
+    /// failing case.
+    pub fn runTestVectors(&self, vectors_json: String) -> Result<String, JsValue> {
+        let vectors = test_vectors::parse(&vectors_json).map_err(error_to_jsvalue)?;
+        let mut cases = Vec::with_capacity(vectors.len());
+        for vector in vectors {
+            if vector.data.is_some() {
+                return Err(error_to_jsvalue(format!(
+                    "test vector '{}' sets 'data', but CompiledPolicy::runTestVectors has no way \
+                     to apply a per-case data override - use RegoVM::runTestVectors instead",
+                    vector.name
+                )));
+            }
+            let input_value = regorus::Value::from_json_str(&vector.input.to_string())
+                .map_err(error_to_jsvalue)?;
+            cases.push(match self.policy.eval_with_input(input_value) {
+                Ok(result) => test_vectors::case_result(
+                    vector.name,
+                    result.to_json_str().map_err(error_to_jsvalue)?,
+                    vector.expected,
+                )?,
+                Err(e) => test_vectors::errored_case(vector.name, vector.expected, e),
+            });
+        }
+        serde_json::to_string(&test_vectors::build_report(cases)).map_err(error_to_jsvalue)
+    }
+
     /// Get the entry point rule for this compiled policy.
     ///
     /// See https://docs.rs/regorus/latest/regorus/struct.CompiledPolicy.html#method.entrypoint
@@ -424,6 +475,60 @@ impl RvmProgram {
         self.program.serialize_binary().map_err(error_to_jsvalue)
     }
 
+    /// Reconstruct a program from bytes produced by [`Self::serializeBinary`], so
+    /// a compiled program can be persisted and reloaded - e.g. with
+    /// `RegoVM::new().loadProgram(RvmProgram.deserializeBinary(cached))` - without
+    /// recompiling from `.rego` source.
+    ///
+    /// The binary format carries its own version/magic header, so a mismatched or
+    /// corrupt blob is rejected with an error here rather than reconstructing a
+    /// program that would silently mis-execute.
+    pub fn deserializeBinary(bytes: Vec<u8>) -> Result<RvmProgram, JsValue> {
+        let program = regorus::rvm::program::Program::deserialize_binary(&bytes)
+            .map_err(error_to_jsvalue)?;
+        Ok(RvmProgram::new(std::sync::Arc::new(program)))
+    }
+
+    /// Serialize this program together with a manifest into a single
+    /// self-describing bundle, for shipping as one artifact whose provenance and
+    /// compatibility can be checked before [`RegoVM::loadProgram`].
+    ///
+    /// * `metadata_json`: JSON object of arbitrary user key/values to carry in the
+    ///   manifest (pass `"{}"` for none). If it contains a `regoVersion` string
+    ///   field, that value is recorded as the manifest's Rego language version;
+    ///   otherwise it defaults to `"v1"`, since a compiled [`RvmProgram`] doesn't
+    ///   otherwise retain which Rego version produced it.
+    pub fn serializeBundle(&self, metadata_json: String) -> Result<Vec<u8>, JsValue> {
+        let metadata: serde_json::Value =
+            serde_json::from_str(&metadata_json).map_err(error_to_jsvalue)?;
+        let program_bytes = self.program.serialize_binary().map_err(error_to_jsvalue)?;
+        let manifest = bundle::BundleManifest {
+            format_version: bundle::FORMAT_VERSION,
+            rego_version: bundle::rego_version_from_metadata(&metadata),
+            entry_points: self.getEntryPointNames(),
+            content_hash: bundle::content_hash(&program_bytes),
+            metadata,
+        };
+        bundle::encode(&manifest, &program_bytes)
+    }
+
+    /// Reconstruct a program from a bundle produced by [`Self::serializeBundle`],
+    /// rejecting it if the format version is unsupported or the content hash
+    /// doesn't match the embedded program bytes.
+    pub fn deserializeBundle(bytes: Vec<u8>) -> Result<RvmProgram, JsValue> {
+        let (manifest, program_bytes) = bundle::decode(&bytes)?;
+        bundle::validate(&manifest, program_bytes)?;
+        RvmProgram::deserializeBinary(program_bytes.to_vec())
+    }
+
+    /// Read a bundle's manifest without decoding the program it carries, so
+    /// provenance/compatibility can be checked cheaply before committing to a full
+    /// load.
+    pub fn getBundleManifest(bytes: Vec<u8>) -> Result<String, JsValue> {
+        let (manifest, _program_bytes) = bundle::decode(&bytes)?;
+        serde_json::to_string(&manifest).map_err(error_to_jsvalue)
+    }
+
     /// Generate assembly listing for this program with default configuration.
     /// * `format`: Assembly format - "readable" or "tabular"
     pub fn getAssemblyListing(&self, format: String) -> String {
@@ -545,6 +650,79 @@ impl RegoVM {
         result.to_json_str().map_err(error_to_jsvalue)
     }
 
+    /// Execute every entry point of the loaded program in one pass, sharing the
+    /// single input/data already bound on this VM, and return a JSON object
+    /// mapping each entry-point name to its computed value. An entry point that
+    /// evaluates to `Undefined` is omitted from the object.
+    pub fn executeAll(&mut self) -> Result<String, JsValue> {
+        let result = self.vm.execute_all().map_err(error_to_jsvalue)?;
+        result.to_json_str().map_err(error_to_jsvalue)
+    }
+
+    /// Evaluate the loaded program against each JSON-encoded input in `inputs` in
+    /// turn, keeping the loaded program and the `data` bound with
+    /// [`Self::setData`] fixed and only swapping `input` between runs - for a
+    /// policy-server host replaying one compiled program over a stream of
+    /// requests. Returns a JSON array, one entry per input in order:
+    /// `{ "result": ..., "instructions_consumed": n, "error": string | null }` -
+    /// `result` is `null` and `error` is set when that input's evaluation failed
+    /// (e.g. hit the instruction budget), rather than aborting the whole batch.
+    ///
+    /// `max_instructions`, if given, temporarily overrides
+    /// [`Self::setInstructionBudget`]/[`Self::setExecutionBudget`]'s configured
+    /// limit for just this batch.
+    pub fn executeBatch(
+        &mut self,
+        inputs: Vec<String>,
+        max_instructions: Option<usize>,
+    ) -> Result<String, JsValue> {
+        let mut parsed_inputs = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            parsed_inputs.push(regorus::Value::from_json_str(&input).map_err(error_to_jsvalue)?);
+        }
+
+        let results = self.vm.execute_batch(parsed_inputs, max_instructions);
+
+        let mut entries = Vec::with_capacity(results.len());
+        for (result, instructions_consumed) in results {
+            let entry = match result {
+                Ok(value) => {
+                    let result_json: serde_json::Value =
+                        serde_json::from_str(&value.to_json_str().map_err(error_to_jsvalue)?)
+                            .map_err(error_to_jsvalue)?;
+                    serde_json::json!({
+                        "result": result_json,
+                        "instructions_consumed": instructions_consumed,
+                        "error": null,
+                    })
+                }
+                Err(e) => serde_json::json!({
+                    "result": null,
+                    "instructions_consumed": instructions_consumed,
+                    "error": e.to_string(),
+                }),
+            };
+            entries.push(entry);
+        }
+
+        serde_json::to_string(&entries).map_err(error_to_jsvalue)
+    }
+
+    /// Designate `name` as the mutating entry point: from now on, [`Self::execute`]
+    /// runs it, requires it to evaluate to an object, and returns
+    /// `{ "allowed": true, "patch": [...] }` - an RFC 6902 JSON Patch from the
+    /// input set with [`Self::setInput`] to that object - instead of the raw rule
+    /// value. Errors if the entry point evaluates to anything other than an object.
+    pub fn setMutatingEntrypoint(&mut self, name: String) {
+        self.vm.set_mutating_entrypoint(Some(&name));
+    }
+
+    /// Undo [`Self::setMutatingEntrypoint`], returning [`Self::execute`] to its
+    /// default behavior of returning the raw entry-point value.
+    pub fn clearMutatingEntrypoint(&mut self) {
+        self.vm.set_mutating_entrypoint(None);
+    }
+
     /// Get the number of entry points available in the loaded program.
     pub fn getEntryPointCount(&self) -> usize {
         self.vm.get_entry_point_count()
@@ -554,6 +732,227 @@ impl RegoVM {
     pub fn getEntryPointNames(&self) -> Vec<String> {
         self.vm.get_entry_point_names()
     }
+
+    /// Execute exactly one instruction at the current program counter and pause.
+    /// Returns a JSON snapshot `{ pc, opcode, disassembled, stack, registers, halted }`
+    /// of the state the VM paused in - call again, or [`Self::continueToBreakpoint`],
+    /// to keep stepping.
+    #[cfg(feature = "rvm-debug")]
+    pub fn stepInto(&mut self) -> Result<String, JsValue> {
+        let halted = self.vm.step().map_err(error_to_jsvalue)?.is_some();
+        self.debugSnapshot(halted)
+    }
+
+    /// Add a breakpoint at instruction offset `addr`.
+    #[cfg(feature = "rvm-debug")]
+    pub fn setBreakpoint(&mut self, addr: usize) {
+        self.vm.add_breakpoint_at_pc(addr);
+    }
+
+    /// Remove a breakpoint previously added with [`Self::setBreakpoint`].
+    #[cfg(feature = "rvm-debug")]
+    pub fn clearBreakpoint(&mut self, addr: usize) {
+        self.vm.clear_breakpoint_at_pc(addr);
+    }
+
+    /// Run from the current program counter until the next breakpoint, a halt, or
+    /// an error, returning the same JSON snapshot shape as [`Self::stepInto`].
+    #[cfg(feature = "rvm-debug")]
+    pub fn continueToBreakpoint(&mut self) -> Result<String, JsValue> {
+        let halted = self.vm.run_debug().map_err(error_to_jsvalue)?.is_some();
+        self.debugSnapshot(halted)
+    }
+
+    /// Rewind to the start of entry point `name` without reloading the program, so
+    /// stepping can be restarted from a clean register/cache state. Breakpoints set
+    /// with [`Self::setBreakpoint`] are left in place.
+    #[cfg(feature = "rvm-debug")]
+    pub fn resetExecution(&mut self, name: String) -> Result<(), JsValue> {
+        self.vm.reset_to_entry_point(&name).map_err(error_to_jsvalue)
+    }
+
+    /// Abort evaluation with a structured "instruction limit exceeded" error instead
+    /// of looping forever once `max_instructions` have been retired by a single
+    /// `execute`/`executeEntryPointByName` call.
+    pub fn setExecutionBudget(&mut self, max_instructions: usize) {
+        self.vm.set_max_instructions(max_instructions);
+    }
+
+    /// Same budget as [`Self::setExecutionBudget`], named for parity with
+    /// [`Self::executeBatch`]'s per-call override - essential when serving
+    /// untrusted policies, so one pathological evaluation can't loop forever
+    /// under load.
+    pub fn setInstructionBudget(&mut self, max_instructions: usize) {
+        self.vm.set_max_instructions(max_instructions);
+    }
+
+    /// Enable or disable the per-opcode/per-entry-point instruction counters
+    /// consulted by [`Self::getExecutionProfile`]. Disabled by default so a caller
+    /// that never asks for a profile doesn't pay the bookkeeping cost.
+    pub fn setProfilingEnabled(&mut self, enable: bool) {
+        self.vm.set_profiling_enabled(enable);
+    }
+
+    /// Enable or disable recording which instructions fire during
+    /// `execute`/`executeAll`/`executeEntryPointBy*`, consulted by
+    /// [`Self::getCoverageReport`]. Disabled by default so a caller that never
+    /// asks for coverage doesn't pay the bookkeeping cost.
+    pub fn setCoverageEnabled(&mut self, enable: bool) {
+        self.vm.set_coverage_enabled(enable);
+    }
+
+    /// Get the coverage report as JSON, in the same `{ files: [{ path, covered,
+    /// not_covered }] }` shape as [`Engine::getCoverageReport`] - except, since
+    /// this build's compiled instructions carry no source file/line metadata,
+    /// `covered`/`not_covered` list raw instruction offsets under a single
+    /// synthetic file rather than real source lines.
+    pub fn getCoverageReport(&self) -> Result<String, JsValue> {
+        self.vm.coverage_report().to_json_str().map_err(error_to_jsvalue)
+    }
+
+    /// Clear gathered coverage data without disabling [`Self::setCoverageEnabled`].
+    pub fn clearCoverageData(&mut self) {
+        self.vm.clear_coverage_data();
+    }
+
+    /// Mirrors OPA's `--strict-builtin-errors`: when enabled, faults that
+    /// [`Self::executeChecked`] would otherwise absorb into `Undefined` and
+    /// report as a diagnostic (arithmetic type errors, division/modulo by zero)
+    /// instead abort evaluation with a hard error. Off by default.
+    pub fn setStrict(&mut self, strict: bool) {
+        self.vm.set_strict(strict);
+    }
+
+    /// Like [`Self::execute`], but instead of conflating a real interpreter fault
+    /// with a policy-authored `null`/undefined result, returns
+    /// `{ "result": ..., "diagnostics": [{ "code", "message", "entry_point",
+    /// "instruction_index", "source_span" }] }` - the same value `execute` would
+    /// return, plus every non-strict fault observed producing it. With
+    /// [`Self::setStrict`] enabled those same faults abort evaluation with a hard
+    /// error instead, so `diagnostics` is then always empty.
+    pub fn executeChecked(&mut self) -> Result<String, JsValue> {
+        let (result, diagnostics) = self.vm.execute_checked().map_err(error_to_jsvalue)?;
+        let result_json: serde_json::Value =
+            serde_json::from_str(&result.to_json_str().map_err(error_to_jsvalue)?)
+                .map_err(error_to_jsvalue)?;
+        let diagnostics_json: Vec<serde_json::Value> = diagnostics
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "code": format!("{:?}", d.code),
+                    "message": d.message,
+                    "entry_point": d.entry_point,
+                    "instruction_index": d.instruction_index,
+                    "source_span": d.source_span,
+                })
+            })
+            .collect();
+        serde_json::to_string(&serde_json::json!({
+            "result": result_json,
+            "diagnostics": diagnostics_json,
+        }))
+        .map_err(error_to_jsvalue)
+    }
+
+    /// Get the profile report for the most recently finished `execute`/
+    /// `executeEntryPointByName`/`executeEntryPointByIndex` call, as JSON:
+    /// `{ instructionsRetired, opcodeHistogram, entryPointInstructionCounts, elapsedMicros }`.
+    /// Returns `null` if profiling wasn't enabled or no run has finished yet.
+    ///
+    /// `elapsedMicros` is always `0` in this build - wiring an actual wall-clock
+    /// source (e.g. `performance.now()`) through [`regorus::rvm::vm::ExecutionClock`]
+    /// is left to a future pass since this crate doesn't otherwise depend on a JS
+    /// timing API.
+    pub fn getExecutionProfile(&mut self) -> Result<String, JsValue> {
+        let profile = match self.vm.take_execution_profile() {
+            Some(profile) => profile,
+            None => return Ok("null".to_string()),
+        };
+        let snapshot = serde_json::json!({
+            "instructionsRetired": profile.instructions_retired,
+            "opcodeHistogram": profile.opcode_histogram,
+            "entryPointInstructionCounts": profile.entry_point_instruction_counts,
+            "elapsedMicros": profile.elapsed_micros,
+        });
+        serde_json::to_string(&snapshot).map_err(error_to_jsvalue)
+    }
+
+    /// Evaluate a fixed batch of test cases against this RVM and report pass/fail
+    /// per case, for conformance/regression testing.
+    ///
+    /// * `vectors_json`: JSON array of `{ name, input, data?, expected }`. When a
+    ///   case carries `data`, it replaces the VM's current data for that case only -
+    ///   the data in effect before this call is restored immediately after the case
+    ///   runs, so one case's `data` override never leaks into the next. `input` is
+    ///   likewise always reset per case.
+    ///
+    /// Returns `{ total, passed, failed, cases: [{ name, status, actual, expected, diff? }] }`,
+    /// where `diff` is the JSON-pointer-style path of the first mismatch for a
+    /// failing case.
+    pub fn runTestVectors(&mut self, vectors_json: String) -> Result<String, JsValue> {
+        let vectors = test_vectors::parse(&vectors_json).map_err(error_to_jsvalue)?;
+        let original_data = self.vm.data().clone();
+        let mut cases = Vec::with_capacity(vectors.len());
+        for vector in vectors {
+            let input_value = regorus::Value::from_json_str(&vector.input.to_string())
+                .map_err(error_to_jsvalue)?;
+            self.vm.set_input(input_value);
+            let has_data_override = vector.data.is_some();
+            if let Some(data) = &vector.data {
+                let data_value =
+                    regorus::Value::from_json_str(&data.to_string()).map_err(error_to_jsvalue)?;
+                self.vm.set_data(data_value).map_err(error_to_jsvalue)?;
+            }
+            cases.push(match self.vm.execute() {
+                Ok(result) => test_vectors::case_result(
+                    vector.name,
+                    result.to_json_str().map_err(error_to_jsvalue)?,
+                    vector.expected,
+                )?,
+                Err(e) => test_vectors::errored_case(vector.name, vector.expected, e),
+            });
+            if has_data_override {
+                self.vm.set_data(original_data.clone()).map_err(error_to_jsvalue)?;
+            }
+        }
+        serde_json::to_string(&test_vectors::build_report(cases)).map_err(error_to_jsvalue)
+    }
+}
+
+#[cfg(feature = "rvm-debug")]
+impl RegoVM {
+    /// Build the `{ pc, opcode, disassembled, stack, registers, halted }` snapshot
+    /// shared by [`Self::stepInto`] and [`Self::continueToBreakpoint`].
+    fn debugSnapshot(&self, halted: bool) -> Result<String, JsValue> {
+        let opcode = self
+            .vm
+            .current_instruction_debug()
+            .unwrap_or_else(|| "<halted>".to_string());
+        let stack: Vec<String> = self
+            .vm
+            .call_rule_stack()
+            .iter()
+            .map(|ctx| format!("rule[{}]", ctx.rule_index))
+            .collect();
+        let mut registers = serde_json::Map::new();
+        for (idx, value) in self.vm.registers().iter().enumerate() {
+            let value_json = value
+                .to_json_str()
+                .map_err(error_to_jsvalue)?
+                .parse::<serde_json::Value>()
+                .map_err(error_to_jsvalue)?;
+            registers.insert(idx.to_string(), value_json);
+        }
+        let snapshot = serde_json::json!({
+            "pc": self.vm.pc(),
+            "opcode": opcode,
+            "disassembled": opcode,
+            "stack": stack,
+            "registers": registers,
+            "halted": halted,
+        });
+        serde_json::to_string(&snapshot).map_err(error_to_jsvalue)
+    }
 }
 
 /// Compile a policy from data and modules with a specific entry point rule.
@@ -614,6 +1013,16 @@ pub fn compileToRvmProgram(
     compiled_policy.compileToRvmProgram(entry_points)
 }
 
+/// Reconstruct an RVM program from bytes produced by [`RvmProgram::serializeBinary`].
+///
+/// This is a standalone equivalent of [`RvmProgram::deserializeBinary`], for hosts
+/// that only have a free-function entry point into the WASM module.
+/// * `bytes`: Serialized program bytes, as returned by `RvmProgram.serializeBinary()`
+#[wasm_bindgen]
+pub fn deserializeRvmProgram(bytes: Vec<u8>) -> Result<RvmProgram, JsValue> {
+    RvmProgram::deserializeBinary(bytes)
+}
+
 /// Generate assembly listing from an RVM program.
 ///
 /// This is a standalone function for generating assembly listings from compiled programs.
@@ -646,6 +1055,548 @@ pub fn generateAssemblyListing(
     }
 }
 
+/// Self-describing bundle format shared by [`RvmProgram::serializeBundle`],
+/// [`RvmProgram::deserializeBundle`] and [`RvmProgram::getBundleManifest`]: a
+/// length-prefixed JSON manifest followed by the raw serialized program bytes.
+///
+/// The manifest's `content_hash` is a fast, non-cryptographic hash of the
+/// serialized program bytes, not of the original source modules - by the time a
+/// program reaches [`RvmProgram`] the source text has already been compiled away,
+/// so hashing the program bytes is what's actually available to detect a bundle
+/// that was truncated or edited in transit.
+mod bundle {
+    use crate::error_to_jsvalue;
+    use serde::{Deserialize, Serialize};
+    use wasm_bindgen::JsValue;
+
+    pub const FORMAT_VERSION: u32 = 1;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct BundleManifest {
+        pub format_version: u32,
+        pub rego_version: String,
+        pub entry_points: Vec<String>,
+        pub content_hash: String,
+        pub metadata: serde_json::Value,
+    }
+
+    /// Read the `regoVersion` field out of the caller's metadata, defaulting to
+    /// `"v1"` (the engine's default) when absent.
+    pub fn rego_version_from_metadata(metadata: &serde_json::Value) -> String {
+        metadata
+            .get("regoVersion")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("v1")
+            .to_string()
+    }
+
+    pub fn content_hash(bytes: &[u8]) -> String {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    pub fn encode(manifest: &BundleManifest, program_bytes: &[u8]) -> Result<Vec<u8>, JsValue> {
+        let manifest_json = serde_json::to_vec(manifest).map_err(error_to_jsvalue)?;
+        let mut bytes = Vec::with_capacity(4 + manifest_json.len() + program_bytes.len());
+        bytes.extend_from_slice(&(manifest_json.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&manifest_json);
+        bytes.extend_from_slice(program_bytes);
+        Ok(bytes)
+    }
+
+    /// Split a bundle into its manifest and the program bytes that follow it,
+    /// without otherwise validating the manifest.
+    pub fn decode(bytes: &[u8]) -> Result<(BundleManifest, &[u8]), JsValue> {
+        if bytes.len() < 4 {
+            return Err(error_to_jsvalue("bundle is too short to contain a manifest length header"));
+        }
+        let manifest_len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let manifest_end = 4usize
+            .checked_add(manifest_len)
+            .ok_or_else(|| error_to_jsvalue("bundle manifest length overflows"))?;
+        let program_bytes = bytes
+            .get(manifest_end..)
+            .ok_or_else(|| error_to_jsvalue("bundle is truncated: declared manifest length exceeds available bytes"))?;
+        let manifest: BundleManifest =
+            serde_json::from_slice(&bytes[4..manifest_end]).map_err(error_to_jsvalue)?;
+        Ok((manifest, program_bytes))
+    }
+
+    /// Reject a decoded manifest whose format version this build doesn't
+    /// understand, or whose content hash doesn't match the program bytes it's
+    /// paired with.
+    pub fn validate(manifest: &BundleManifest, program_bytes: &[u8]) -> Result<(), JsValue> {
+        if manifest.format_version != FORMAT_VERSION {
+            return Err(error_to_jsvalue(format!(
+                "unsupported bundle format version {} (expected {FORMAT_VERSION})",
+                manifest.format_version
+            )));
+        }
+        if manifest.content_hash != content_hash(program_bytes) {
+            return Err(error_to_jsvalue(
+                "bundle content hash does not match its program bytes - bundle may be corrupted or tampered with",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Batch conformance testing shared by [`CompiledPolicy::runTestVectors`] and
+/// [`RegoVM::runTestVectors`]: parsing the `{ name, input, data?, expected }` vector
+/// array, deep-comparing a case's actual result against its expected value, and
+/// assembling the `{ total, passed, failed, cases }` report.
+mod test_vectors {
+    use crate::error_to_jsvalue;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use wasm_bindgen::JsValue;
+
+    #[derive(Deserialize)]
+    pub struct TestVector {
+        pub name: String,
+        pub input: Value,
+        #[serde(default)]
+        pub data: Option<Value>,
+        pub expected: Value,
+    }
+
+    #[derive(Serialize)]
+    pub struct CaseResult {
+        pub name: String,
+        pub status: String,
+        pub actual: Value,
+        pub expected: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub diff: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    pub struct Report {
+        pub total: usize,
+        pub passed: usize,
+        pub failed: usize,
+        pub cases: Vec<CaseResult>,
+    }
+
+    pub fn parse(vectors_json: &str) -> Result<Vec<TestVector>, serde_json::Error> {
+        serde_json::from_str(vectors_json)
+    }
+
+    /// Build a [`CaseResult`] for a case that evaluated successfully, deep-comparing
+    /// `actual_json` against `expected`.
+    pub fn case_result(
+        name: String,
+        actual_json: String,
+        expected: Value,
+    ) -> Result<CaseResult, JsValue> {
+        let actual: Value = serde_json::from_str(&actual_json).map_err(error_to_jsvalue)?;
+        let diff = first_mismatch_path(&actual, &expected);
+        let status = if diff.is_none() { "passed" } else { "failed" };
+        Ok(CaseResult {
+            name,
+            status: status.to_string(),
+            actual,
+            expected,
+            diff,
+        })
+    }
+
+    /// Build a [`CaseResult`] for a case that failed to evaluate at all.
+    pub fn errored_case<E: std::fmt::Display>(name: String, expected: Value, error: E) -> CaseResult {
+        CaseResult {
+            name,
+            status: "failed".to_string(),
+            actual: Value::Null,
+            expected,
+            diff: Some(format!("evaluation error: {error}")),
+        }
+    }
+
+    pub fn build_report(cases: Vec<CaseResult>) -> Report {
+        let total = cases.len();
+        let passed = cases.iter().filter(|c| c.status == "passed").count();
+        Report {
+            total,
+            passed,
+            failed: total - passed,
+            cases,
+        }
+    }
+
+    /// JSON-pointer-style path (`/foo/0/bar`) of the first point where `actual` and
+    /// `expected` diverge, or `None` if they're deeply equal. Keys are walked in
+    /// `expected`'s order so the reported path always reflects what the case
+    /// expected to find.
+    fn first_mismatch_path(actual: &Value, expected: &Value) -> Option<String> {
+        fn walk(actual: &Value, expected: &Value, path: &mut String) -> bool {
+            if actual == expected {
+                return true;
+            }
+            match (actual, expected) {
+                (Value::Object(a), Value::Object(e)) => {
+                    if a.len() != e.len() {
+                        return false;
+                    }
+                    for (key, evalue) in e {
+                        let Some(avalue) = a.get(key) else {
+                            path.push('/');
+                            path.push_str(key);
+                            return false;
+                        };
+                        let prefix_len = path.len();
+                        path.push('/');
+                        path.push_str(key);
+                        if !walk(avalue, evalue, path) {
+                            return false;
+                        }
+                        path.truncate(prefix_len);
+                    }
+                    true
+                }
+                (Value::Array(a), Value::Array(e)) => {
+                    if a.len() != e.len() {
+                        return false;
+                    }
+                    for (index, (avalue, evalue)) in a.iter().zip(e.iter()).enumerate() {
+                        let prefix_len = path.len();
+                        path.push('/');
+                        path.push_str(&index.to_string());
+                        if !walk(avalue, evalue, path) {
+                            return false;
+                        }
+                        path.truncate(prefix_len);
+                    }
+                    true
+                }
+                _ => false,
+            }
+        }
+        let mut path = String::new();
+        if walk(actual, expected, &mut path) {
+            None
+        } else {
+            Some(if path.is_empty() {
+                "/".to_string()
+            } else {
+                path
+            })
+        }
+    }
+}
+
+/// Structured lint diagnostics for [`Engine::lintPolicies`].
+///
+/// `Engine::get_ast_as_json` is the only AST surface available here - this crate
+/// depends on `regorus` purely through its public API, which never exposes the
+/// typed AST, only this JSON dump - so every rule below walks the parsed
+/// `serde_json::Value` tree by field name rather than matching on AST node enums.
+/// Field names are best-effort guesses at regorus's documented AST JSON shape
+/// (`imports`/`head`/`location` and friends); a rule that can't find the fields it
+/// expects simply reports nothing for that node rather than guessing further. This
+/// crate has no way to independently confirm `get_ast_as_json`'s real shape against
+/// a running `regorus` build, so `tests::lint_policies_test` is the actual check on
+/// whether these guesses are right: it feeds each rule a module that should trip it
+/// and asserts the matching `rule_id` comes back, rather than just checking that
+/// `lintPolicies` returns parseable JSON. A failure there means a guess below needs
+/// correcting against the real schema, not that the test is wrong.
+///
+/// An "unused variable" rule (assigned but never referenced) is deliberately not
+/// included: telling an assignment occurrence from a reference occurrence needs
+/// real scope tracking, which isn't recoverable from this JSON without a much
+/// better-founded guess at the schema than the rules below already are - left for a
+/// follow-up pass once this crate has typed AST access.
+#[cfg(feature = "ast")]
+mod lint {
+    use serde::Serialize;
+    use serde_json::Value;
+
+    #[derive(Serialize, Clone)]
+    pub struct Span {
+        pub start: u64,
+        pub end: u64,
+    }
+
+    #[derive(Serialize, Clone)]
+    pub struct SuggestedEdit {
+        pub span: Span,
+        pub replacement: String,
+    }
+
+    #[derive(Serialize)]
+    pub struct Diagnostic {
+        pub rule_id: String,
+        pub severity: String,
+        pub message: String,
+        pub file: String,
+        pub span: Span,
+        pub suggested_edits: Vec<SuggestedEdit>,
+    }
+
+    /// One independent lint check, visiting a single module's AST. New rules are
+    /// added to [`default_rules`] without `run` needing to change.
+    trait Rule {
+        fn id(&self) -> &'static str;
+        fn severity(&self) -> &'static str;
+        fn check(&self, file: &str, module: &Value) -> Vec<Diagnostic>;
+    }
+
+    /// Run every rule in [`default_rules`] over every module in `ast`, which is
+    /// either a single module object or an array of them.
+    pub fn run(ast: &Value) -> Vec<Diagnostic> {
+        let modules: Vec<&Value> = match ast {
+            Value::Array(modules) => modules.iter().collect(),
+            other => vec![other],
+        };
+        let rules = default_rules();
+        modules
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, module)| {
+                let file = module_file(module, index);
+                rules
+                    .iter()
+                    .flat_map(move |rule| rule.check(&file, module))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn default_rules() -> Vec<Box<dyn Rule>> {
+        vec![
+            Box::new(ShadowsBuiltinRule),
+            Box::new(AlwaysTrueFalseComparisonRule),
+            Box::new(UnusedImportRule),
+        ]
+    }
+
+    fn module_file(module: &Value, index: usize) -> String {
+        for key in ["file", "path", "source"] {
+            if let Some(name) = module.get(key).and_then(Value::as_str) {
+                return name.to_string();
+            }
+        }
+        format!("<module {index}>")
+    }
+
+    /// Reads a `location`-shaped node's byte range. Tries `start`/`end` first, then
+    /// falls back to treating `row`/`col` as a zero-width point location, then `(0,
+    /// 0)` if neither is present.
+    fn span_from_location(location: Option<&Value>) -> Span {
+        let Some(location) = location else {
+            return Span { start: 0, end: 0 };
+        };
+        let as_u64 = |key: &str| location.get(key).and_then(Value::as_u64);
+        if let (Some(start), Some(end)) = (as_u64("start"), as_u64("end")) {
+            return Span { start, end };
+        }
+        if let Some(row) = as_u64("row") {
+            return Span {
+                start: row,
+                end: row,
+            };
+        }
+        Span { start: 0, end: 0 }
+    }
+
+    /// Recursively visit `value` and every value nested inside it, depth-first.
+    fn walk<'a>(value: &'a Value, visit: &mut dyn FnMut(&'a Value)) {
+        visit(value);
+        match value {
+            Value::Object(map) => {
+                for child in map.values() {
+                    walk(child, visit);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    walk(item, visit);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Rego rule heads that shadow one of these names make the built-in unreachable
+    /// by unqualified reference (`count(x)`) anywhere later in the same package.
+    const BUILTIN_NAMES: &[&str] = &[
+        "count", "sum", "max", "min", "sort", "contains", "startswith", "endswith",
+        "sprintf", "concat", "split", "trim", "lower", "upper", "type", "all", "any",
+        "object", "array", "json", "input", "data",
+    ];
+
+    /// Flags a rule/function head whose name shadows a built-in name, making the
+    /// built-in unreachable by its usual unqualified name within the package.
+    struct ShadowsBuiltinRule;
+
+    impl Rule for ShadowsBuiltinRule {
+        fn id(&self) -> &'static str {
+            "shadows-builtin"
+        }
+
+        fn severity(&self) -> &'static str {
+            "warning"
+        }
+
+        fn check(&self, file: &str, module: &Value) -> Vec<Diagnostic> {
+            let mut diagnostics = Vec::new();
+            walk(module, &mut |node| {
+                let Some(head) = node.get("head").and_then(Value::as_object) else {
+                    return;
+                };
+                let Some(name) = head.get("name").and_then(Value::as_str) else {
+                    return;
+                };
+                if !BUILTIN_NAMES.contains(&name) {
+                    return;
+                }
+                diagnostics.push(Diagnostic {
+                    rule_id: self.id().to_string(),
+                    severity: self.severity().to_string(),
+                    message: format!("rule `{name}` shadows the built-in function of the same name"),
+                    file: file.to_string(),
+                    span: span_from_location(head.get("location")),
+                    suggested_edits: Vec::new(),
+                });
+            });
+            diagnostics
+        }
+    }
+
+    /// Flags an equality/inequality comparison whose two operands are structurally
+    /// identical, which can only ever evaluate to the same result regardless of
+    /// input - almost always a typo for comparing two different terms.
+    struct AlwaysTrueFalseComparisonRule;
+
+    impl AlwaysTrueFalseComparisonRule {
+        const EQ_OPS: &'static [&'static str] = &["Eq", "==", "Equal"];
+        const NEQ_OPS: &'static [&'static str] = &["Neq", "!=", "NotEqual"];
+    }
+
+    impl Rule for AlwaysTrueFalseComparisonRule {
+        fn id(&self) -> &'static str {
+            "always-true-false-comparison"
+        }
+
+        fn severity(&self) -> &'static str {
+            "warning"
+        }
+
+        fn check(&self, file: &str, module: &Value) -> Vec<Diagnostic> {
+            let mut diagnostics = Vec::new();
+            walk(module, &mut |node| {
+                let Some(obj) = node.as_object() else {
+                    return;
+                };
+                let Some(op) = obj
+                    .get("op")
+                    .or_else(|| obj.get("operator"))
+                    .and_then(Value::as_str)
+                else {
+                    return;
+                };
+                let (Some(lhs), Some(rhs)) = (
+                    obj.get("lhs").or_else(|| obj.get("left")),
+                    obj.get("rhs").or_else(|| obj.get("right")),
+                ) else {
+                    return;
+                };
+                if lhs != rhs {
+                    return;
+                }
+                let verdict = if Self::EQ_OPS.contains(&op) {
+                    "always true"
+                } else if Self::NEQ_OPS.contains(&op) {
+                    "always false"
+                } else {
+                    return;
+                };
+                diagnostics.push(Diagnostic {
+                    rule_id: self.id().to_string(),
+                    severity: self.severity().to_string(),
+                    message: format!("comparison is {verdict}: both sides are the same expression"),
+                    file: file.to_string(),
+                    span: span_from_location(obj.get("location")),
+                    suggested_edits: Vec::new(),
+                });
+            });
+            diagnostics
+        }
+    }
+
+    /// Flags an import whose bound name never occurs again anywhere else in the
+    /// module. The bound name is approximated as the last string literal found
+    /// inside the import node (e.g. the `bar` in `import data.foo.bar`), since the
+    /// JSON doesn't expose a single clearly-named "bound identifier" field.
+    struct UnusedImportRule;
+
+    impl Rule for UnusedImportRule {
+        fn id(&self) -> &'static str {
+            "unused-import"
+        }
+
+        fn severity(&self) -> &'static str {
+            "warning"
+        }
+
+        fn check(&self, file: &str, module: &Value) -> Vec<Diagnostic> {
+            let Some(imports) = module.get("imports").and_then(Value::as_array) else {
+                return Vec::new();
+            };
+            let mut diagnostics = Vec::new();
+            for import in imports {
+                let mut last_string = None;
+                // `walk(module, ...)` below necessarily re-visits every node under
+                // `import`'s own subtree (recursion happens in `walk` itself,
+                // regardless of what the visit closure does), so a single
+                // `std::ptr::eq(node, import)` check - which only skips the
+                // import's own root node - doesn't stop `name` from reappearing
+                // when it's walked again as part of `module`. Collect every node
+                // under `import` up front and skip all of them, not just its root.
+                let mut import_nodes: std::collections::HashSet<*const Value> =
+                    std::collections::HashSet::new();
+                walk(import, &mut |node| {
+                    import_nodes.insert(node as *const Value);
+                    if let Value::String(s) = node {
+                        last_string = Some(s.clone());
+                    }
+                });
+                let Some(name) = last_string else {
+                    continue;
+                };
+                let mut used_elsewhere = false;
+                walk(module, &mut |node| {
+                    if used_elsewhere {
+                        return;
+                    }
+                    if import_nodes.contains(&(node as *const Value)) {
+                        return;
+                    }
+                    if matches!(node, Value::String(s) if s == &name) {
+                        used_elsewhere = true;
+                    }
+                });
+                if !used_elsewhere {
+                    let span = span_from_location(import.get("location"));
+                    diagnostics.push(Diagnostic {
+                        rule_id: self.id().to_string(),
+                        severity: self.severity().to_string(),
+                        message: format!("import `{name}` is never referenced"),
+                        file: file.to_string(),
+                        span: span.clone(),
+                        suggested_edits: vec![SuggestedEdit {
+                            span,
+                            replacement: String::new(),
+                        }],
+                    });
+                }
+            }
+            diagnostics
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{error_to_jsvalue, PolicyModule, RegoVM};
@@ -865,4 +1816,553 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(feature = "rvm-debug")]
+    #[wasm_bindgen_test]
+    pub fn rvm_debugger_test() -> Result<(), JsValue> {
+        // Test single-stepping, breakpoints and reset through the WASM debugger API
+        let module = PolicyModule::new(
+            "debug_test.rego".to_string(),
+            r#"package debug_test
+            result := input.value * 2"#.to_string(),
+        );
+
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.debug_test.result".to_string()],
+        )?;
+
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+        vm.setInput(r#"{"value": 21}"#.to_string())?;
+
+        // Stepping one instruction at a time should never report halted until the
+        // program actually completes, and the snapshot should always be valid JSON
+        // with a pc field.
+        let snapshot = regorus::Value::from_json_str(&vm.stepInto()?).map_err(error_to_jsvalue)?;
+        assert_eq!(snapshot["halted"], regorus::Value::from(false));
+        assert!(snapshot["pc"].as_i64().is_ok());
+
+        // No breakpoint is set yet, so continuing should run the rest of the
+        // program to completion and report halted.
+        let snapshot =
+            regorus::Value::from_json_str(&vm.continueToBreakpoint()?).map_err(error_to_jsvalue)?;
+        assert_eq!(snapshot["halted"], regorus::Value::from(true));
+
+        // Reset back to the entry point and step again from scratch.
+        vm.resetExecution("data.debug_test.result".to_string())?;
+        let snapshot = regorus::Value::from_json_str(&vm.stepInto()?).map_err(error_to_jsvalue)?;
+        assert!(snapshot["pc"].as_i64().is_ok());
+        assert_eq!(snapshot["halted"], regorus::Value::from(false));
+
+        // Breakpoints can be set and cleared without affecting normal execution.
+        vm.setBreakpoint(0);
+        vm.clearBreakpoint(0);
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn rvm_profiling_test() -> Result<(), JsValue> {
+        // Test execution profiling and the instruction budget through the WASM API
+        let module = PolicyModule::new(
+            "profile_test.rego".to_string(),
+            r#"package profile_test
+            result := input.value * 2"#.to_string(),
+        );
+
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.profile_test.result".to_string()],
+        )?;
+
+        // Profiling disabled by default: no profile is available after executing.
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+        vm.setInput(r#"{"value": 21}"#.to_string())?;
+        vm.execute()?;
+        assert_eq!(vm.getExecutionProfile()?, "null");
+
+        // Once enabled, a profile with a non-empty opcode histogram is available.
+        vm.setProfilingEnabled(true);
+        vm.execute()?;
+        let profile =
+            regorus::Value::from_json_str(&vm.getExecutionProfile()?).map_err(error_to_jsvalue)?;
+        assert!(profile["instructionsRetired"].as_i64().map_err(error_to_jsvalue)? > 0);
+        assert!(!profile["opcodeHistogram"]
+            .as_object()
+            .map_err(error_to_jsvalue)?
+            .is_empty());
+
+        // A budget lower than the program's instruction count aborts with a
+        // structured error instead of returning a result.
+        vm.setExecutionBudget(1);
+        assert!(vm.execute().is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "ast")]
+    #[wasm_bindgen_test]
+    pub fn lint_policies_test() -> Result<(), JsValue> {
+        // One module per rule under test, each written so it should trip exactly
+        // one lint rule and nothing else, so a missing `rule_id` below points
+        // straight at which rule's field-name guess against `get_ast_as_json`'s
+        // output (see the `lint` module's doc comment - its exact shape is not
+        // independently confirmed here) didn't match.
+        let mut engine = crate::Engine::new();
+        engine.addPolicy(
+            "shadows_builtin.rego".to_string(),
+            r#"package lint_test_shadows
+            count := 5"#
+                .to_string(),
+        )?;
+        engine.addPolicy(
+            "always_true.rego".to_string(),
+            r#"package lint_test_tautology
+            allow := input.user == input.user"#
+                .to_string(),
+        )?;
+        engine.addPolicy(
+            "unused_import.rego".to_string(),
+            r#"package lint_test_unused_import
+            import data.unused_package
+            allow := true"#
+                .to_string(),
+        )?;
+
+        let diagnostics_json = engine.lintPolicies()?;
+        let diagnostics = regorus::Value::from_json_str(&diagnostics_json)
+            .map_err(error_to_jsvalue)?
+            .as_array()
+            .map_err(error_to_jsvalue)?
+            .clone();
+
+        let has_rule_id = |id: &str| {
+            diagnostics
+                .iter()
+                .any(|d| d["rule_id"] == regorus::Value::from(id))
+        };
+
+        assert!(
+            has_rule_id("shadows-builtin"),
+            "expected a shadows-builtin diagnostic for `count := 5`, got {diagnostics:?}"
+        );
+        assert!(
+            has_rule_id("always-true-false-comparison"),
+            "expected an always-true-false-comparison diagnostic for `input.user == input.user`, got {diagnostics:?}"
+        );
+        assert!(
+            has_rule_id("unused-import"),
+            "expected an unused-import diagnostic for `import data.unused_package`, got {diagnostics:?}"
+        );
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn run_test_vectors_test() -> Result<(), JsValue> {
+        // Test the conformance test-vector runner on both CompiledPolicy and RegoVM.
+        let mut engine = crate::Engine::new();
+        engine.addPolicy(
+            "vectors_test.rego".to_string(),
+            r#"package vectors_test
+            result := input.value * 2"#.to_string(),
+        )?;
+        let compiled_policy = engine.compileWithEntrypoint("data.vectors_test.result".to_string())?;
+
+        let vectors_json = r#"[
+            {"name": "doubles", "input": {"value": 21}, "expected": 42},
+            {"name": "wrong", "input": {"value": 1}, "expected": 99}
+        ]"#
+        .to_string();
+
+        let report =
+            regorus::Value::from_json_str(&compiled_policy.runTestVectors(vectors_json.clone())?)
+                .map_err(error_to_jsvalue)?;
+        assert_eq!(report["total"], regorus::Value::from(2));
+        assert_eq!(report["passed"], regorus::Value::from(1));
+        assert_eq!(report["failed"], regorus::Value::from(1));
+        assert_eq!(report["cases"][0]["status"], regorus::Value::from("passed"));
+        assert_eq!(report["cases"][1]["status"], regorus::Value::from("failed"));
+        assert!(report["cases"][1]["diff"].as_string().is_ok());
+
+        let module = PolicyModule::new(
+            "vectors_test.rego".to_string(),
+            r#"package vectors_test
+            result := input.value * 2"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.vectors_test.result".to_string()],
+        )?;
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+
+        let report = regorus::Value::from_json_str(&vm.runTestVectors(vectors_json)?)
+            .map_err(error_to_jsvalue)?;
+        assert_eq!(report["total"], regorus::Value::from(2));
+        assert_eq!(report["passed"], regorus::Value::from(1));
+        assert_eq!(report["failed"], regorus::Value::from(1));
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn run_test_vectors_data_override_test() -> Result<(), JsValue> {
+        // RegoVM::runTestVectors: a case's `data` override should apply only to
+        // that case, not leak into the next one that supplies no `data` of its own.
+        let module = PolicyModule::new(
+            "vectors_data_test.rego".to_string(),
+            r#"package vectors_data_test
+            result := data.factor * input.value"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{"factor": 1}"#.to_string(),
+            vec![module],
+            vec!["data.vectors_data_test.result".to_string()],
+        )?;
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+
+        let vectors_json = r#"[
+            {"name": "overridden", "input": {"value": 2}, "data": {"factor": 10}, "expected": 20},
+            {"name": "baseline", "input": {"value": 2}, "expected": 2}
+        ]"#
+        .to_string();
+        let report = regorus::Value::from_json_str(&vm.runTestVectors(vectors_json)?)
+            .map_err(error_to_jsvalue)?;
+        assert_eq!(report["passed"], regorus::Value::from(2));
+        assert_eq!(report["failed"], regorus::Value::from(0));
+
+        // CompiledPolicy has no per-call data override to honor a case's `data`
+        // with, so it errors instead of silently ignoring it.
+        let mut engine = crate::Engine::new();
+        engine.addDataJson(r#"{"factor": 1}"#.to_string())?;
+        engine.addPolicy(
+            "vectors_data_test2.rego".to_string(),
+            r#"package vectors_data_test2
+            result := data.factor * input.value"#.to_string(),
+        )?;
+        let compiled_policy =
+            engine.compileWithEntrypoint("data.vectors_data_test2.result".to_string())?;
+        let data_vectors_json = r#"[
+            {"name": "wants data", "input": {"value": 2}, "data": {"factor": 10}, "expected": 20}
+        ]"#
+        .to_string();
+        assert!(compiled_policy.runTestVectors(data_vectors_json).is_err());
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn rvm_program_bundle_test() -> Result<(), JsValue> {
+        // Test round-tripping a program through a self-describing bundle, and
+        // reading its manifest back out without decoding the program.
+        let module = PolicyModule::new(
+            "bundle_test.rego".to_string(),
+            r#"package bundle_test
+            result := input.value * 2"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.bundle_test.result".to_string()],
+        )?;
+
+        let bytes = program.serializeBundle(r#"{"builtBy": "ci"}"#.to_string())?;
+
+        let manifest = regorus::Value::from_json_str(&crate::RvmProgram::getBundleManifest(
+            bytes.clone(),
+        )?)
+        .map_err(error_to_jsvalue)?;
+        assert_eq!(manifest["format_version"], regorus::Value::from(1));
+        assert_eq!(manifest["rego_version"], regorus::Value::from("v1"));
+        assert_eq!(manifest["metadata"]["builtBy"], regorus::Value::from("ci"));
+        assert!(manifest["entry_points"]
+            .as_array()
+            .map_err(error_to_jsvalue)?
+            .contains(&regorus::Value::from("data.bundle_test.result")));
+
+        let reloaded = crate::RvmProgram::deserializeBundle(bytes.clone())?;
+        assert_eq!(reloaded.getEntryPointCount(), program.getEntryPointCount());
+
+        // A bundle whose bytes were tampered with after serialization should be
+        // rejected rather than silently loaded.
+        let mut tampered = bytes;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xFF;
+        assert!(crate::RvmProgram::deserializeBundle(tampered).is_err());
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn rvm_program_binary_roundtrip_test() -> Result<(), JsValue> {
+        // Test that a program reloaded from serializeBinary()/deserializeBinary()
+        // reports identical metadata to the original, through both the method and
+        // free-function entry points.
+        let module = PolicyModule::new(
+            "roundtrip_test.rego".to_string(),
+            r#"package roundtrip_test
+            allow := input.value > 0
+            deny := input.value < 0"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec![
+                "data.roundtrip_test.allow".to_string(),
+                "data.roundtrip_test.deny".to_string(),
+            ],
+        )?;
+
+        let bytes = program.serializeBinary()?;
+
+        let reloaded = crate::RvmProgram::deserializeBinary(bytes.clone())?;
+        assert_eq!(reloaded.getInstructionCount(), program.getInstructionCount());
+        assert_eq!(reloaded.getEntryPointCount(), program.getEntryPointCount());
+        let mut entry_points = reloaded.getEntryPointNames();
+        entry_points.sort();
+        let mut expected_entry_points = program.getEntryPointNames();
+        expected_entry_points.sort();
+        assert_eq!(entry_points, expected_entry_points);
+
+        // The free function is equivalent to the static method.
+        let reloaded = crate::deserializeRvmProgram(bytes)?;
+        assert_eq!(reloaded.getInstructionCount(), program.getInstructionCount());
+
+        // A corrupt/truncated blob is rejected rather than producing a program
+        // that would silently mis-execute.
+        assert!(crate::RvmProgram::deserializeBinary(vec![0u8; 3]).is_err());
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn execute_all_test() -> Result<(), JsValue> {
+        // Test that executeAll() evaluates every entry point in one pass and
+        // omits entry points that evaluate to undefined.
+        let module = PolicyModule::new(
+            "execute_all_test.rego".to_string(),
+            r#"package execute_all_test
+            allow := input.value > 0
+            deny := input.value < 0
+            message := "shared" if allow"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec![
+                "data.execute_all_test.allow".to_string(),
+                "data.execute_all_test.deny".to_string(),
+                "data.execute_all_test.message".to_string(),
+            ],
+        )?;
+
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+        vm.setInput(r#"{"value": 5}"#.to_string())?;
+
+        let results = regorus::Value::from_json_str(&vm.executeAll()?).map_err(error_to_jsvalue)?;
+        assert_eq!(
+            results["data.execute_all_test.allow"],
+            regorus::Value::from(true)
+        );
+        assert_eq!(
+            results["data.execute_all_test.message"],
+            regorus::Value::from("shared")
+        );
+        // `deny` is undefined for a positive value, so it's omitted entirely.
+        assert_eq!(results["data.execute_all_test.deny"], regorus::Value::Undefined);
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn mutating_entrypoint_test() -> Result<(), JsValue> {
+        // Test that setMutatingEntrypoint() makes execute() return
+        // { allowed, patch } describing the diff from input to the rule's result,
+        // and that clearing it restores the raw rule value.
+        let module = PolicyModule::new(
+            "mutate_test.rego".to_string(),
+            r#"package mutate_test
+            mutated := {"value": input.value, "approved": true} if input.value > 0"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.mutate_test.mutated".to_string()],
+        )?;
+
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+        vm.setInput(r#"{"value": 5}"#.to_string())?;
+
+        vm.setMutatingEntrypoint("data.mutate_test.mutated".to_string());
+        let result = regorus::Value::from_json_str(&vm.execute()?).map_err(error_to_jsvalue)?;
+        assert_eq!(result["allowed"], regorus::Value::from(true));
+        let patch = match &result["patch"] {
+            regorus::Value::Array(patch) => patch,
+            other => panic!("expected patch array, got {other:?}"),
+        };
+        assert!(patch.iter().any(|op| {
+            op["op"] == regorus::Value::from("add") && op["path"] == regorus::Value::from("/approved")
+        }));
+
+        // A mutating entry point that doesn't evaluate to an object is rejected.
+        vm.setInput(r#"{"value": -1}"#.to_string())?;
+        assert!(vm.execute().is_err());
+
+        // Clearing the designation restores the raw rule value.
+        vm.clearMutatingEntrypoint();
+        vm.setInput(r#"{"value": 5}"#.to_string())?;
+        let raw = regorus::Value::from_json_str(&vm.execute()?).map_err(error_to_jsvalue)?;
+        assert_eq!(raw["approved"], regorus::Value::from(true));
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn coverage_report_test() -> Result<(), JsValue> {
+        // Test that setCoverageEnabled()/getCoverageReport()/clearCoverageData()
+        // report a non-empty set of covered instructions once enabled, and an
+        // empty one again after clearing.
+        let module = PolicyModule::new(
+            "coverage_test.rego".to_string(),
+            r#"package coverage_test
+            allow := input.value > 0"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.coverage_test.allow".to_string()],
+        )?;
+
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+        vm.setInput(r#"{"value": 5}"#.to_string())?;
+
+        // No coverage is recorded until it's enabled.
+        let report = regorus::Value::from_json_str(&vm.getCoverageReport()?).map_err(error_to_jsvalue)?;
+        let covered = match &report["files"][0]["covered"] {
+            regorus::Value::Array(covered) => covered.clone(),
+            other => panic!("expected covered array, got {other:?}"),
+        };
+        assert!(covered.is_empty());
+
+        vm.setCoverageEnabled(true);
+        vm.execute()?;
+        let report = regorus::Value::from_json_str(&vm.getCoverageReport()?).map_err(error_to_jsvalue)?;
+        let covered = match &report["files"][0]["covered"] {
+            regorus::Value::Array(covered) => covered.clone(),
+            other => panic!("expected covered array, got {other:?}"),
+        };
+        assert!(!covered.is_empty());
+
+        vm.clearCoverageData();
+        let report = regorus::Value::from_json_str(&vm.getCoverageReport()?).map_err(error_to_jsvalue)?;
+        let covered = match &report["files"][0]["covered"] {
+            regorus::Value::Array(covered) => covered.clone(),
+            other => panic!("expected covered array, got {other:?}"),
+        };
+        assert!(covered.is_empty());
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn execute_checked_test() -> Result<(), JsValue> {
+        // Test that executeChecked() reports a division-by-zero fault as a
+        // diagnostic (with the result resolving to undefined) in lenient mode,
+        // and as a hard error once setStrict(true) is set.
+        let module = PolicyModule::new(
+            "checked_test.rego".to_string(),
+            r#"package checked_test
+            result := input.numerator / input.denominator"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.checked_test.result".to_string()],
+        )?;
+
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+        vm.setInput(r#"{"numerator": 10, "denominator": 0}"#.to_string())?;
+
+        // `Value::Undefined` has no JSON representation, so it round-trips as `null`.
+        let checked = regorus::Value::from_json_str(&vm.executeChecked()?).map_err(error_to_jsvalue)?;
+        assert_eq!(checked["result"], regorus::Value::Null);
+        let diagnostics = match &checked["diagnostics"] {
+            regorus::Value::Array(diagnostics) => diagnostics.clone(),
+            other => panic!("expected diagnostics array, got {other:?}"),
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["code"], regorus::Value::from("DivisionByZero"));
+
+        vm.setStrict(true);
+        assert!(vm.executeChecked().is_err());
+
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    pub fn execute_batch_test() -> Result<(), JsValue> {
+        // Test that executeBatch() evaluates each input against the one loaded
+        // program, reports instructions_consumed per item, and that a per-call
+        // instruction budget override turns one item into an error without
+        // aborting the rest of the batch.
+        let module = PolicyModule::new(
+            "batch_test.rego".to_string(),
+            r#"package batch_test
+            result := input.value * 2"#.to_string(),
+        );
+        let program = crate::compileToRvmProgram(
+            r#"{}"#.to_string(),
+            vec![module],
+            vec!["data.batch_test.result".to_string()],
+        )?;
+
+        let mut vm = RegoVM::new();
+        vm.loadProgram(&program)?;
+
+        let inputs = vec![
+            r#"{"value": 1}"#.to_string(),
+            r#"{"value": 2}"#.to_string(),
+            r#"{"value": 3}"#.to_string(),
+        ];
+        let batch = regorus::Value::from_json_str(&vm.executeBatch(inputs.clone(), None)?)
+            .map_err(error_to_jsvalue)?;
+        let entries = match &batch {
+            regorus::Value::Array(entries) => entries.clone(),
+            other => panic!("expected array of batch entries, got {other:?}"),
+        };
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["result"], regorus::Value::from(2));
+        assert_eq!(entries[1]["result"], regorus::Value::from(4));
+        assert_eq!(entries[2]["result"], regorus::Value::from(6));
+        for entry in entries.iter() {
+            assert_ne!(entry["instructions_consumed"], regorus::Value::from(0));
+            assert_eq!(entry["error"], regorus::Value::Null);
+        }
+
+        // A tiny per-call budget override turns every item into an error instead
+        // of aborting the whole batch.
+        let batch = regorus::Value::from_json_str(&vm.executeBatch(inputs, Some(1))?)
+            .map_err(error_to_jsvalue)?;
+        let entries = match &batch {
+            regorus::Value::Array(entries) => entries.clone(),
+            other => panic!("expected array of batch entries, got {other:?}"),
+        };
+        for entry in entries.iter() {
+            assert_eq!(entry["result"], regorus::Value::Null);
+            assert_ne!(entry["error"], regorus::Value::Null);
+        }
+
+        Ok(())
+    }
 }